@@ -0,0 +1,237 @@
+// SPDX-FileCopyrightText: 2021 Softbear, Inc.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::id::{RegionId, ServerId};
+use crate::UnixTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Wallclock age, in milliseconds, past which a record's server is presumed dying and in need of
+/// DNS replacement.
+pub const STALENESS_THRESHOLD_MILLIS: u64 = 15_000;
+
+/// A server's contribution to the shared membership view, versioned so concurrent updates from
+/// different peers converge. Modeled on Solana's cluster_info CRDT values.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VersionedServerRecord {
+    /// Region the server is in.
+    pub region_id: Option<RegionId>,
+    /// Whether the server is currently serving players.
+    pub healthy: bool,
+    /// Number of (real) players.
+    pub player_count: u32,
+    /// What server this server is redirecting to, if any.
+    pub redirect_server_id: Option<ServerId>,
+    /// Hash of the client the server is serving, for version-skew detection.
+    pub client_hash: Option<u64>,
+    /// When the owning server last updated this record.
+    pub wallclock: UnixTime,
+    /// Monotonic version assigned by the owning server; higher always wins on merge.
+    pub version: u64,
+}
+
+impl VersionedServerRecord {
+    /// Whether `other` should replace `self` on merge: `other` has a strictly higher version, or an
+    /// equal version with a newer wallclock.
+    fn is_superseded_by(&self, other: &VersionedServerRecord) -> bool {
+        other.version > self.version
+            || (other.version == self.version && other.wallclock > self.wallclock)
+    }
+}
+
+/// An eventually-consistent view of cluster membership, replacing the ad-hoc
+/// [`StatusResponse`](crate::rpc::StatusResponse) fields with a convergent map that tolerates
+/// partitions.
+#[cfg(feature = "server")]
+#[derive(Clone, Debug, Default)]
+pub struct ClusterInfo {
+    records: HashMap<ServerId, VersionedServerRecord>,
+}
+
+#[cfg(feature = "server")]
+impl ClusterInfo {
+    /// Overwrite this server's own record with a freshly-versioned one, bumping the version so the
+    /// update wins over stale copies elsewhere in the cluster.
+    pub fn refresh_local(&mut self, me: ServerId, mut record: VersionedServerRecord) {
+        let version = self
+            .records
+            .get(&me)
+            .map(|existing| existing.version + 1)
+            .unwrap_or(0);
+        record.version = version;
+        self.records.insert(me, record);
+    }
+
+    /// Merge a peer's record for `server_id`, keeping whichever record wins by version (ties broken
+    /// by wallclock). Returns true if the local view changed.
+    pub fn merge(&mut self, server_id: ServerId, record: VersionedServerRecord) -> bool {
+        match self.records.get(&server_id) {
+            Some(existing) if !existing.is_superseded_by(&record) => false,
+            _ => {
+                self.records.insert(server_id, record);
+                true
+            }
+        }
+    }
+
+    /// Merge a batch of records pushed or pulled from a peer, returning true if anything changed.
+    pub fn merge_all(
+        &mut self,
+        records: impl IntoIterator<Item = (ServerId, VersionedServerRecord)>,
+    ) -> bool {
+        let mut changed = false;
+        for (server_id, record) in records {
+            changed |= self.merge(server_id, record);
+        }
+        changed
+    }
+
+    /// Records updated at or after `since`, to push to a random subset of peers.
+    pub fn updated_since(
+        &self,
+        since: UnixTime,
+    ) -> Vec<(ServerId, VersionedServerRecord)> {
+        self.records
+            .iter()
+            .filter(|(_, record)| record.wallclock >= since)
+            .map(|(id, record)| (*id, record.clone()))
+            .collect()
+    }
+
+    /// The server ids currently known, so a peer can diff against its own set and pull any it is
+    /// missing.
+    pub fn known_ids(&self) -> impl Iterator<Item = ServerId> + '_ {
+        self.records.keys().copied()
+    }
+
+    /// The records this server holds for the ids `wanted` lists, to answer a pull request. Ids it
+    /// has no record for are skipped.
+    pub fn records_for(
+        &self,
+        wanted: impl IntoIterator<Item = ServerId>,
+    ) -> Vec<(ServerId, VersionedServerRecord)> {
+        wanted
+            .into_iter()
+            .filter_map(|id| self.records.get(&id).map(|record| (id, record.clone())))
+            .collect()
+    }
+
+    /// Dying servers, derived from records whose wallclock is older than
+    /// [`STALENESS_THRESHOLD_MILLIS`] relative to `now`, or which report themselves unhealthy.
+    pub fn dying_server_ids(&self, now: UnixTime) -> Vec<ServerId> {
+        let cutoff = now.saturating_sub(STALENESS_THRESHOLD_MILLIS);
+        self.records
+            .iter()
+            .filter(|(_, record)| !record.healthy || record.wallclock < cutoff)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// The merged membership view, for surfacing through the
+    /// [`SystemUpdate`](crate::rpc::SystemUpdate) channel.
+    pub fn records(&self) -> impl Iterator<Item = (ServerId, &VersionedServerRecord)> {
+        self.records.iter().map(|(id, record)| (*id, record))
+    }
+}
+
+/// One gossip round: push our recently-updated records to a small random subset of `peers`, and
+/// issue pull requests to fetch records we may be missing. Returns the peers to contact and the
+/// payload to send each.
+#[cfg(feature = "server")]
+pub fn gossip_round(
+    cluster: &ClusterInfo,
+    peers: &[ServerId],
+    fanout: usize,
+    since: UnixTime,
+    rng: &mut impl rand::Rng,
+) -> Vec<(ServerId, GossipMessage)> {
+    use rand::seq::SliceRandom;
+
+    let push = cluster.updated_since(since);
+    let known: Vec<ServerId> = cluster.known_ids().collect();
+
+    let mut chosen: Vec<ServerId> = peers.to_vec();
+    chosen.shuffle(rng);
+    chosen.truncate(fanout);
+
+    chosen
+        .into_iter()
+        .map(|peer| {
+            (
+                peer,
+                GossipMessage {
+                    push: push.clone(),
+                    pull_known: known.clone(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// The wire payload exchanged in a gossip round: records we are pushing, and the ids we already
+/// know so the peer can push back anything we are missing.
+#[cfg(feature = "server")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GossipMessage {
+    pub push: Vec<(ServerId, VersionedServerRecord)>,
+    pub pull_known: Vec<ServerId>,
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU8;
+
+    fn server(n: u8) -> ServerId {
+        ServerId(NonZeroU8::new(n).unwrap())
+    }
+
+    fn record(version: u64, wallclock: UnixTime, healthy: bool) -> VersionedServerRecord {
+        VersionedServerRecord {
+            region_id: None,
+            healthy,
+            player_count: 0,
+            redirect_server_id: None,
+            client_hash: None,
+            wallclock,
+            version,
+        }
+    }
+
+    #[test]
+    fn merge_keeps_higher_version() {
+        let mut cluster = ClusterInfo::default();
+        assert!(cluster.merge(server(1), record(2, 100, true)));
+        // Lower version loses and reports no change.
+        assert!(!cluster.merge(server(1), record(1, 999, true)));
+        // Higher version wins.
+        assert!(cluster.merge(server(1), record(3, 50, true)));
+        assert_eq!(cluster.records().next().unwrap().1.version, 3);
+    }
+
+    #[test]
+    fn merge_breaks_version_ties_by_wallclock() {
+        let mut cluster = ClusterInfo::default();
+        cluster.merge(server(1), record(5, 100, true));
+        // Equal version, newer wallclock wins.
+        assert!(cluster.merge(server(1), record(5, 200, true)));
+        assert_eq!(cluster.records().next().unwrap().1.wallclock, 200);
+        // Equal version, older wallclock loses.
+        assert!(!cluster.merge(server(1), record(5, 150, true)));
+    }
+
+    #[test]
+    fn dying_servers_derived_from_staleness_and_health() {
+        let mut cluster = ClusterInfo::default();
+        let now = STALENESS_THRESHOLD_MILLIS + 1_000;
+        cluster.merge(server(1), record(0, now, true)); // fresh
+        cluster.merge(server(2), record(0, 0, true)); // stale wallclock
+        cluster.merge(server(3), record(0, now, false)); // unhealthy
+
+        let dying = cluster.dying_server_ids(now);
+        assert_eq!(dying.len(), 2);
+        assert!(dying.contains(&server(2)));
+        assert!(dying.contains(&server(3)));
+        assert!(!dying.contains(&server(1)));
+    }
+}
@@ -0,0 +1,192 @@
+// SPDX-FileCopyrightText: 2021 Softbear, Inc.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::id::PlayerId;
+use crate::priority::Priority;
+use crate::rpc::{AdminRequest, Request, Update};
+use std::collections::VecDeque;
+
+/// Where an [`Outbound`] update is headed once the transport drains it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Destination {
+    /// Back to the connection the triggering request arrived on.
+    Sender,
+    /// To a specific player, wherever they are connected.
+    Player(PlayerId),
+    /// To every connected player.
+    Broadcast,
+}
+
+/// An [`Update`] produced by a [`Handler`], tagged with its destination and priority band so the
+/// transport can route and schedule it without re-inspecting the payload.
+#[derive(Clone, Debug)]
+pub struct Outbound<T> {
+    pub to: Destination,
+    pub priority: Priority,
+    pub message: T,
+}
+
+impl<GU> Outbound<Update<GU>> {
+    /// An update addressed back to the sender, at the band implied by its variant.
+    pub fn reply(message: Update<GU>) -> Self {
+        Outbound {
+            priority: message.priority(),
+            to: Destination::Sender,
+            message,
+        }
+    }
+
+    /// An update broadcast to every player, at the band implied by its variant.
+    pub fn broadcast(message: Update<GU>) -> Self {
+        Outbound {
+            priority: message.priority(),
+            to: Destination::Broadcast,
+            message,
+        }
+    }
+
+    /// An update addressed to a single player, at the band implied by its variant.
+    pub fn to_player(player_id: PlayerId, message: Update<GU>) -> Self {
+        Outbound {
+            priority: message.priority(),
+            to: Destination::Player(player_id),
+            message,
+        }
+    }
+}
+
+/// Computes the side-effects of an inbound request. Decoupled from actix plumbing so a request's
+/// outputs can be asserted without a running network: feed the inbox, run [`Mailbox::process`],
+/// inspect the outbox.
+pub trait Handler<GR, GU> {
+    /// Handle a game/chat/etc. request from `from`, returning the updates to enqueue.
+    fn handle(&mut self, from: PlayerId, request: Request<GR>) -> Vec<Outbound<Update<GU>>>;
+
+    /// Handle an admin request. Defaults to producing nothing for games that expose no admin
+    /// surface.
+    fn handle_admin(&mut self, _request: AdminRequest) -> Vec<Outbound<Update<GU>>> {
+        Vec::new()
+    }
+}
+
+/// An item waiting in the [`Mailbox`] inbox.
+enum Inbound<GR> {
+    Request(PlayerId, Request<GR>),
+    Admin(AdminRequest),
+}
+
+/// A typed inbox/outbox pair decoupling game logic from the transport. Inbound requests land in the
+/// inbox, a [`Handler`] turns them into updates, and produced updates queue in the outbox for the
+/// transport to drain. Generic over `GR`/`GU` so both the core game and the admin interface reuse
+/// it.
+pub struct Mailbox<GR, GU> {
+    inbox: VecDeque<Inbound<GR>>,
+    outbox: VecDeque<Outbound<Update<GU>>>,
+    /// Upper bound on queued inbound requests, for backpressure.
+    capacity: usize,
+}
+
+impl<GR, GU> Mailbox<GR, GU> {
+    /// Create a mailbox that buffers at most `capacity` pending inbound requests.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inbox: VecDeque::new(),
+            outbox: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Enqueue an inbound request. Returns false (dropping the request) when the inbox is full, the
+    /// single point at which inbound backpressure is enforced.
+    pub fn receive(&mut self, from: PlayerId, request: Request<GR>) -> bool {
+        if self.inbox.len() >= self.capacity {
+            return false;
+        }
+        self.inbox.push_back(Inbound::Request(from, request));
+        true
+    }
+
+    /// Enqueue an inbound admin request, subject to the same backpressure.
+    pub fn receive_admin(&mut self, request: AdminRequest) -> bool {
+        if self.inbox.len() >= self.capacity {
+            return false;
+        }
+        self.inbox.push_back(Inbound::Admin(request));
+        true
+    }
+
+    /// Run every queued inbound request through `handler`, appending its updates to the outbox.
+    pub fn process(&mut self, handler: &mut impl Handler<GR, GU>) {
+        while let Some(inbound) = self.inbox.pop_front() {
+            let produced = match inbound {
+                Inbound::Request(from, request) => handler.handle(from, request),
+                Inbound::Admin(request) => handler.handle_admin(request),
+            };
+            self.outbox.extend(produced);
+        }
+    }
+
+    /// Pop the next update the transport should send, or `None` when the outbox is empty.
+    pub fn drain(&mut self) -> Option<Outbound<Update<GU>>> {
+        self.outbox.pop_front()
+    }
+
+    /// Whether any produced updates are waiting to be drained.
+    pub fn has_outbound(&self) -> bool {
+        !self.outbox.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{ClientRequest, ClientUpdate};
+    use std::num::NonZeroU32;
+
+    fn player(n: u32) -> PlayerId {
+        PlayerId(NonZeroU32::new(n).unwrap())
+    }
+
+    /// Acknowledges every `Trace` back to its sender, so tests can observe the request→update
+    /// mapping without a running network.
+    struct Echo;
+
+    impl Handler<(), ()> for Echo {
+        fn handle(&mut self, from: PlayerId, request: Request<()>) -> Vec<Outbound<Update<()>>> {
+            match request {
+                Request::Client(ClientRequest::Trace { .. }) => {
+                    vec![Outbound::to_player(from, Update::Client(ClientUpdate::Traced))]
+                }
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn process_maps_inbox_to_outbox() {
+        let mut mailbox = Mailbox::<(), ()>::new(8);
+        assert!(mailbox.receive(
+            player(1),
+            Request::Client(ClientRequest::Trace {
+                message: "hi".to_owned()
+            })
+        ));
+
+        let mut handler = Echo;
+        mailbox.process(&mut handler);
+
+        let out = mailbox.drain().expect("one update produced");
+        assert_eq!(out.to, Destination::Player(player(1)));
+        assert_eq!(out.priority, Priority::Normal);
+        assert!(matches!(out.message, Update::Client(ClientUpdate::Traced)));
+        assert!(mailbox.drain().is_none());
+    }
+
+    #[test]
+    fn receive_enforces_backpressure_at_capacity() {
+        let mut mailbox = Mailbox::<(), ()>::new(1);
+        assert!(mailbox.receive(player(1), Request::Client(ClientRequest::TallyFps(1.0))));
+        // Inbox is full; the next request is dropped rather than queued unbounded.
+        assert!(!mailbox.receive(player(1), Request::Client(ClientRequest::TallyFps(2.0))));
+    }
+}
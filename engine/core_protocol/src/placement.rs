@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2021 Softbear, Inc.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::id::{RegionId, ServerId};
+use crate::rpc::SystemQuery;
+
+/// A server considered for placement by [`select`].
+#[cfg(feature = "server")]
+#[derive(Copy, Clone, Debug)]
+pub struct Candidate {
+    pub server_id: ServerId,
+    pub region_id: Option<RegionId>,
+    pub healthy: bool,
+    /// Number of (real) players currently on the server.
+    pub player_count: u32,
+    /// Maximum number of players the server is willing to host.
+    pub capacity: u32,
+}
+
+#[cfg(feature = "server")]
+impl Candidate {
+    /// Placement weight, inversely related to load and clamped to at least one so a full server is
+    /// still reachable (and never has zero probability).
+    fn weight(&self) -> f64 {
+        self.capacity.saturating_sub(self.player_count).max(1) as f64
+    }
+}
+
+/// Choose a server for a `query`.
+///
+/// An explicit, honorable `server_id` preference wins first; otherwise load-weighted random
+/// selection spreads new players across under-loaded servers rather than piling onto whichever one
+/// DNS returned. `invitation_id` preferences are resolved by the caller and passed as `preferred`.
+#[cfg(feature = "server")]
+pub fn select(
+    query: &SystemQuery,
+    preferred: Option<ServerId>,
+    candidates: &[Candidate],
+    rng: &mut impl rand::Rng,
+) -> Option<ServerId> {
+    let honor = |id: ServerId| {
+        candidates
+            .iter()
+            .find(|c| c.server_id == id && c.healthy)
+            .map(|c| c.server_id)
+    };
+
+    // Honor invitation/server preferences first.
+    if let Some(id) = query
+        .server_id
+        .and_then(honor)
+        .or_else(|| preferred.and_then(honor))
+    {
+        return Some(id);
+    }
+
+    let eligible: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| c.healthy && c.region_id == query.region_id)
+        .collect();
+
+    weighted_best(&eligible, rng).map(|c| c.server_id)
+}
+
+/// Pick the candidate with the largest Efraimidis–Spirakis key `k_i = u_i^(1/w_i)`, the same
+/// weighted-sampling-without-replacement primitive behind Solana's `weighted_best`.
+#[cfg(feature = "server")]
+pub fn weighted_best<'a>(
+    candidates: &[&'a Candidate],
+    rng: &mut impl rand::Rng,
+) -> Option<&'a Candidate> {
+    candidates
+        .iter()
+        .map(|c| (key(c, rng), *c))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, c)| c)
+}
+
+/// Rank all candidates by descending key, producing a full weighted fallback list.
+#[cfg(feature = "server")]
+pub fn weighted_shuffle<'a>(
+    candidates: &[&'a Candidate],
+    rng: &mut impl rand::Rng,
+) -> Vec<&'a Candidate> {
+    let mut keyed: Vec<(f64, &Candidate)> = candidates.iter().map(|c| (key(c, rng), *c)).collect();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    keyed.into_iter().map(|(_, c)| c).collect()
+}
+
+/// The Efraimidis–Spirakis key for a single candidate: `u^(1/w)` for a uniform `u ∈ (0, 1)`.
+#[cfg(feature = "server")]
+fn key(candidate: &Candidate, rng: &mut impl rand::Rng) -> f64 {
+    // Exclude 0 so `u` lies in the open interval and every key is positive.
+    let u: f64 = rng.gen_range(f64::MIN_POSITIVE..1.0);
+    u.powf(1.0 / candidate.weight())
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU8;
+
+    fn server(n: u8) -> ServerId {
+        ServerId(NonZeroU8::new(n).unwrap())
+    }
+
+    fn candidate(n: u8, healthy: bool, player_count: u32) -> Candidate {
+        Candidate {
+            server_id: server(n),
+            region_id: None,
+            healthy,
+            player_count,
+            capacity: 100,
+        }
+    }
+
+    #[test]
+    fn honors_healthy_server_preference_before_weighted_draw() {
+        let mut rng = rand::thread_rng();
+        let query = SystemQuery {
+            server_id: Some(server(2)),
+            region_id: None,
+            invitation_id: None,
+        };
+        let candidates = [candidate(1, true, 0), candidate(2, true, 90)];
+        // The preference wins even though server 2 is the more loaded candidate.
+        assert_eq!(select(&query, None, &candidates, &mut rng), Some(server(2)));
+    }
+
+    #[test]
+    fn falls_back_to_invitation_preference() {
+        let mut rng = rand::thread_rng();
+        let query = SystemQuery {
+            server_id: None,
+            region_id: None,
+            invitation_id: None,
+        };
+        let candidates = [candidate(1, true, 0), candidate(2, true, 0)];
+        assert_eq!(
+            select(&query, Some(server(2)), &candidates, &mut rng),
+            Some(server(2))
+        );
+    }
+
+    #[test]
+    fn skips_unhealthy_preference_and_draws_from_region() {
+        let mut rng = rand::thread_rng();
+        let query = SystemQuery {
+            server_id: Some(server(1)),
+            region_id: None,
+            invitation_id: None,
+        };
+        // Preferred server is unhealthy, so it falls through to the weighted draw over the only
+        // healthy candidate.
+        let candidates = [candidate(1, false, 0), candidate(2, true, 0)];
+        assert_eq!(select(&query, None, &candidates, &mut rng), Some(server(2)));
+    }
+}
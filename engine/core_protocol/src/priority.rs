@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2021 Softbear, Inc.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::rpc::Update;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Maximum number of payload bytes carried by a single [`Chunk`]. A large
+/// [`Update`] is split across several chunks so that it never monopolizes the
+/// socket.
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Priority band of an outgoing frame. Latency-sensitive traffic uses [`Priority::High`]
+/// so it is not delayed behind bulk board churn. Stored as a `u8` to keep frames small.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum Priority {
+    /// Player, chat, and game traffic.
+    High = 0,
+    /// Default band for anything not otherwise classified.
+    Normal = 1,
+    /// Leaderboard, liveboard, and system traffic, which may carry large `Arc<[...]>` payloads.
+    Low = 2,
+}
+
+impl Priority {
+    /// The bands, highest first, for round-robin iteration.
+    pub const BANDS: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+}
+
+impl<GU> Update<GU> {
+    /// The [`Priority`] band this update is scheduled under.
+    pub fn priority(&self) -> Priority {
+        match self {
+            Update::Chat(_) | Update::Game(_) | Update::Player(_) => Priority::High,
+            Update::Client(_) | Update::Invitation(_) | Update::Team(_) => Priority::Normal,
+            Update::Leaderboard(_) | Update::Liveboard(_) | Update::System(_) => Priority::Low,
+        }
+    }
+}
+
+/// Monotonically increasing identifier of a logical message, used by the client to
+/// reassemble chunks that arrived interleaved with other priorities.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MessageId(pub u32);
+
+/// A bounded slice of a serialized [`Update`], tagged so the receiver can reassemble it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The message this chunk belongs to.
+    pub id: MessageId,
+    /// The band the message was enqueued under.
+    pub priority: Priority,
+    /// Serialized bytes, at most [`CHUNK_SIZE`] long.
+    pub payload: Vec<u8>,
+    /// Whether this is the final chunk of the message.
+    pub last: bool,
+}
+
+/// Client-side accumulator that reassembles messages from chunks arriving interleaved across
+/// priority bands on the one socket. Partial payloads are keyed by [`MessageId`], since the
+/// [`Scheduler`] may emit a chunk of one message between two chunks of another.
+#[derive(Default)]
+pub struct Reassembler {
+    partial: std::collections::HashMap<MessageId, Vec<u8>>,
+}
+
+impl Reassembler {
+    /// Append `chunk` to its message's partial payload, returning the completed payload once that
+    /// message's final chunk arrives. Chunks of a given message are in order, but chunks of
+    /// different messages may be interleaved, so each id keeps its own buffer.
+    pub fn accept(&mut self, chunk: Chunk) -> Option<Vec<u8>> {
+        let payload = self.partial.entry(chunk.id).or_default();
+        payload.extend_from_slice(&chunk.payload);
+        if chunk.last {
+            self.partial.remove(&chunk.id)
+        } else {
+            None
+        }
+    }
+}
+
+/// Server-side scheduler that round-robins serialized chunks across priority classes on a
+/// single socket, so a high-priority update enqueued mid-transmission of a large low-priority
+/// one is sent on the next chunk boundary rather than after it completes.
+#[cfg(feature = "server")]
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: u32,
+    /// One queue of pending chunks per band, indexed by `Priority as usize`.
+    bands: [VecDeque<Chunk>; 3],
+    /// The band the next [`Scheduler::next`] call starts from (the one after the band it last
+    /// serviced), for fair rotation.
+    cursor: usize,
+}
+
+#[cfg(feature = "server")]
+impl Scheduler {
+    /// Split `serialized` into [`CHUNK_SIZE`]-bounded chunks tagged with a fresh [`MessageId`]
+    /// and enqueue them in the `priority` band.
+    pub fn enqueue(&mut self, priority: Priority, serialized: &[u8]) -> MessageId {
+        let id = MessageId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let band = &mut self.bands[priority as usize];
+        let mut chunks = serialized.chunks(CHUNK_SIZE).peekable();
+        // An empty message still produces one (empty, final) chunk.
+        if chunks.peek().is_none() {
+            band.push_back(Chunk {
+                id,
+                priority,
+                payload: Vec::new(),
+                last: true,
+            });
+        } else {
+            while let Some(payload) = chunks.next() {
+                band.push_back(Chunk {
+                    id,
+                    priority,
+                    payload: payload.to_vec(),
+                    last: chunks.peek().is_none(),
+                });
+            }
+        }
+        id
+    }
+
+    /// Pop the next chunk to write, round-robining across non-empty bands starting from the band
+    /// after the one last serviced, so no band is starved and a high-priority update interleaves
+    /// with an in-flight low-priority one. Returns `None` when every band is empty.
+    pub fn next(&mut self) -> Option<Chunk> {
+        for offset in 0..self.bands.len() {
+            let band = (self.cursor + offset) % self.bands.len();
+            if let Some(chunk) = self.bands[band].pop_front() {
+                // Advance past the serviced band so the next call rotates, interleaving a
+                // high-priority update with an in-flight low-priority one.
+                self.cursor = (band + 1) % self.bands.len();
+                return Some(chunk);
+            }
+        }
+        None
+    }
+
+    /// Whether any band still has chunks waiting to be written.
+    pub fn is_empty(&self) -> bool {
+        self.bands.iter().all(VecDeque::is_empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: u32, priority: Priority, payload: &[u8], last: bool) -> Chunk {
+        Chunk {
+            id: MessageId(id),
+            priority,
+            payload: payload.to_vec(),
+            last,
+        }
+    }
+
+    #[test]
+    fn reassembler_recovers_interleaved_messages() {
+        let mut r = Reassembler::default();
+        // id=3 (low) starts, id=5 (high) arrives mid-stream, then id=3 finishes.
+        assert_eq!(r.accept(chunk(3, Priority::Low, b"lo", false)), None);
+        assert_eq!(
+            r.accept(chunk(5, Priority::High, b"high", true)).as_deref(),
+            Some(&b"high"[..])
+        );
+        assert_eq!(
+            r.accept(chunk(3, Priority::Low, b"w", true)).as_deref(),
+            Some(&b"low"[..])
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn scheduler_splits_into_bounded_chunks() {
+        let mut s = Scheduler::default();
+        let payload = vec![0u8; CHUNK_SIZE * 2 + 1];
+        s.enqueue(Priority::Low, &payload);
+
+        let mut sizes = Vec::new();
+        while let Some(c) = s.next() {
+            assert!(c.payload.len() <= CHUNK_SIZE);
+            sizes.push(c.payload.len());
+        }
+        assert_eq!(sizes, vec![CHUNK_SIZE, CHUNK_SIZE, 1]);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn scheduler_interleaves_across_bands() {
+        let mut s = Scheduler::default();
+        let low = s.enqueue(Priority::Low, &vec![0u8; CHUNK_SIZE * 2]);
+
+        // One low chunk goes out, then a high-priority update is enqueued mid-transmission...
+        assert_eq!(s.next().unwrap().id, low);
+        let high = s.enqueue(Priority::High, b"hi");
+
+        // ...and it is sent on the next chunk boundary, before the remaining low chunk.
+        assert_eq!(s.next().unwrap().id, high);
+        assert_eq!(s.next().unwrap().id, low);
+        assert!(s.next().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+// SPDX-FileCopyrightText: 2021 Softbear, Inc.
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::priority::MessageId;
+use crate::rpc::Update;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Hard cap on the number of payload bytes in a single [`StreamFrame`]. Oversized input chunks
+/// are split so no frame exceeds this, mirroring netapp's 16k packet limit.
+pub const MAX_STREAM_CHUNK: usize = 16 * 1024;
+
+/// Identifies a side channel correlated with the [`MessageId`] of the main [`Update`] that opened
+/// it, so the receiver can route frames to the right consumer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct StreamId(pub u32);
+
+/// One bounded frame of an associated stream. The stream terminates on the frame with `end` set,
+/// which is always emitted exactly once (and carries no payload).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamFrame {
+    /// The main message this stream is associated with.
+    pub id: MessageId,
+    /// The side channel within that message.
+    pub stream: StreamId,
+    /// At most [`MAX_STREAM_CHUNK`] bytes; empty on the end-of-stream frame.
+    pub payload: Bytes,
+    /// End-of-stream marker.
+    pub end: bool,
+}
+
+impl StreamFrame {
+    /// Split `bytes` into frames no larger than [`MAX_STREAM_CHUNK`], none of them marked `end`.
+    pub fn split(id: MessageId, stream: StreamId, bytes: Bytes) -> impl Iterator<Item = StreamFrame> {
+        let mut remaining = bytes;
+        std::iter::from_fn(move || {
+            if remaining.is_empty() {
+                None
+            } else {
+                let take = remaining.len().min(MAX_STREAM_CHUNK);
+                let payload = remaining.split_to(take);
+                Some(StreamFrame {
+                    id,
+                    stream,
+                    payload,
+                    end: false,
+                })
+            }
+        })
+    }
+
+    /// The terminating frame, carrying no payload.
+    pub fn eos(id: MessageId, stream: StreamId) -> StreamFrame {
+        StreamFrame {
+            id,
+            stream,
+            payload: Bytes::new(),
+            end: true,
+        }
+    }
+}
+
+/// An [`Update`] paired with an optional associated stream, so large payloads can be pushed as
+/// bounded [`StreamFrame`]s over a side channel instead of one multi-megabyte enum value.
+#[cfg(feature = "server")]
+pub struct StreamingUpdate<GU> {
+    /// The main update, sent first.
+    pub update: Update<GU>,
+    /// The side channel of [`Bytes`] chunks, if any.
+    pub stream: Option<(StreamId, futures::stream::BoxStream<'static, Bytes>)>,
+}
+
+#[cfg(feature = "server")]
+impl<GU> Update<GU> {
+    /// Attach an associated stream to this update. The transport drains `stream`, splitting each
+    /// item to [`MAX_STREAM_CHUNK`] and appending a single end-of-stream frame once it completes.
+    pub fn with_stream<S>(self, stream: S) -> StreamingUpdate<GU>
+    where
+        S: futures::Stream<Item = Bytes> + Send + 'static,
+    {
+        use futures::StreamExt;
+        StreamingUpdate {
+            update: self,
+            stream: Some((StreamId(0), stream.boxed())),
+        }
+    }
+
+    /// Wrap this update with no associated stream.
+    pub fn without_stream(self) -> StreamingUpdate<GU> {
+        StreamingUpdate {
+            update: self,
+            stream: None,
+        }
+    }
+}
+
+/// Drive `stream` to completion, emitting bounded [`StreamFrame`]s via `sink` followed by exactly
+/// one end-of-stream frame, so the receiver terminates cleanly even when the payload length is a
+/// multiple of [`MAX_STREAM_CHUNK`].
+#[cfg(feature = "server")]
+pub async fn pump<S, F>(
+    id: MessageId,
+    stream: StreamId,
+    mut source: S,
+    mut sink: F,
+) where
+    S: futures::Stream<Item = Bytes> + Unpin,
+    F: FnMut(StreamFrame),
+{
+    use futures::StreamExt;
+    while let Some(bytes) = source.next().await {
+        for frame in StreamFrame::split(id, stream, bytes) {
+            sink(frame);
+        }
+    }
+    sink(StreamFrame::eos(id, stream));
+}
+
+/// Client-side receiver of an associated stream, keyed by the correlated `(MessageId, StreamId)`.
+/// Yields each payload chunk in order and completes when the end-of-stream frame arrives.
+pub struct StreamReceiver {
+    rx: futures::channel::mpsc::UnboundedReceiver<Bytes>,
+}
+
+impl StreamReceiver {
+    /// Create a receiver and the sender the transport feeds decoded frames into.
+    pub fn new() -> (StreamSender, StreamReceiver) {
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        (StreamSender { tx }, StreamReceiver { rx })
+    }
+}
+
+impl futures::Stream for StreamReceiver {
+    type Item = Bytes;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Bytes>> {
+        std::pin::Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+/// The transport half feeding decoded [`StreamFrame`]s to a [`StreamReceiver`].
+pub struct StreamSender {
+    tx: futures::channel::mpsc::UnboundedSender<Bytes>,
+}
+
+impl StreamSender {
+    /// Route a decoded frame to the receiver, closing the channel on end-of-stream. Returns false
+    /// once the receiver has been dropped.
+    pub fn accept(&self, frame: StreamFrame) -> bool {
+        if frame.end {
+            let open = !self.tx.is_closed();
+            self.tx.close_channel();
+            open
+        } else {
+            self.tx.unbounded_send(frame.payload).is_ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_bounds_each_frame() {
+        let bytes = Bytes::from(vec![0u8; MAX_STREAM_CHUNK + 1]);
+        let frames: Vec<_> = StreamFrame::split(MessageId(1), StreamId(0), bytes).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].payload.len(), MAX_STREAM_CHUNK);
+        assert_eq!(frames[1].payload.len(), 1);
+        assert!(frames.iter().all(|f| !f.end));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn pump_emits_lone_eos_on_exact_multiple() {
+        use futures::executor::block_on;
+
+        // A payload that is an exact multiple of the chunk size must still terminate with exactly
+        // one zero-length end-of-stream frame.
+        let source = futures::stream::iter(vec![Bytes::from(vec![0u8; MAX_STREAM_CHUNK * 2])]);
+        let mut frames = Vec::new();
+        block_on(pump(MessageId(7), StreamId(0), source, |f| frames.push(f)));
+
+        assert_eq!(frames.len(), 3);
+        assert!(!frames[0].end && !frames[1].end);
+        assert_eq!(frames[0].payload.len(), MAX_STREAM_CHUNK);
+        assert_eq!(frames[1].payload.len(), MAX_STREAM_CHUNK);
+        assert!(frames[2].end && frames[2].payload.is_empty());
+        assert_eq!(frames.iter().filter(|f| f.end).count(), 1);
+    }
+}